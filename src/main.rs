@@ -1,78 +1,498 @@
-use hound::{SampleFormat, WavReader};
-use std::{fs::File, io::Write, path::Path, time::Instant};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+    sync::{atomic::AtomicUsize, atomic::Ordering, Arc, Mutex},
+    time::Instant,
+};
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer};
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
 use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
 
 const MODEL_PATH: &str = "models/ggml-large-v3.bin";
+const WHISPER_SAMPLE_RATE: u32 = 16000;
+const STREAM_WINDOW_SECONDS: f64 = 8.0;
+const STREAM_OVERLAP_SECONDS: f64 = 1.0;
 
 fn main() {
-    let audio_path = "audio_en.wav";
-    let output_path = "transcription_en.txt";
+    let mut format = None;
+    let mut language = "en".to_string();
+    let mut translate = false;
+    let mut jobs: usize = 1;
+    let mut stream = false;
+    let mut audio_paths = Vec::new();
+    for arg in std::env::args().skip(1) {
+        if let Some(value) = arg.strip_prefix("--format=") {
+            format = Some(
+                OutputFormat::parse(value)
+                    .unwrap_or_else(|| panic!("Unknown output format: {}", value)),
+            );
+        } else if let Some(value) = arg.strip_prefix("--language=") {
+            language = value.to_string();
+        } else if arg == "--translate" {
+            translate = true;
+        } else if let Some(value) = arg.strip_prefix("--jobs=") {
+            jobs = value
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid job count: {}", value));
+        } else if arg == "--stream" {
+            stream = true;
+        } else {
+            audio_paths.push(arg);
+        }
+    }
 
-    // 1. Check files
+    // 1. Check the model is present either way
     if !Path::new(MODEL_PATH).exists() {
         panic!("Model {} not found! Download it with:\nwget https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-large-v3.bin -O {}", MODEL_PATH, MODEL_PATH);
     }
 
-    if !Path::new(audio_path).exists() {
-        panic!("Audio file {} not found!", audio_path);
+    // 2. Load model once, behind an Arc so the worker pool (and streaming mode) can share it read-only
+    println!("[1/2] Loading model...");
+    let ctx = Arc::new(
+        WhisperContext::new_with_params(MODEL_PATH, WhisperContextParameters::default())
+            .expect("Error loading model"),
+    );
+
+    if stream {
+        println!("[2/2] Streaming from stdin (16 kHz mono f32 PCM)...");
+        run_streaming(&ctx, &language, translate);
+        return;
+    }
+
+    let audio_paths = if audio_paths.is_empty() {
+        vec!["audio_en.wav".to_string()]
+    } else {
+        audio_paths
+    };
+    for audio_path in &audio_paths {
+        if !Path::new(audio_path).exists() {
+            panic!("Audio file {} not found!", audio_path);
+        }
+    }
+
+    // 3. Transcribe each file with its own decode state, in parallel if --jobs > 1
+    let format = format.unwrap_or(OutputFormat::Txt);
+    let batch_start = Instant::now();
+    println!(
+        "[2/2] Transcribing {} file(s) across {} worker(s)...",
+        audio_paths.len(),
+        jobs
+    );
+
+    let audio_buffers: Vec<Vec<f32>> = audio_paths.iter().map(|p| load_audio(p)).collect();
+    let states = transcribe_parallel(&ctx, &audio_buffers, &language, translate, jobs);
+
+    for (audio_path, mut state) in audio_paths.iter().zip(states) {
+        let detected_language = if language == "auto" {
+            let (name, probability) = detect_language(&mut state);
+            println!(
+                "  -> {}: detected language {} ({:.1}%)",
+                audio_path,
+                name,
+                probability * 100.0
+            );
+            Some((name, probability))
+        } else {
+            None
+        };
+
+        let output_path = output_path_for(audio_path, format);
+        save_results(
+            &state,
+            &output_path,
+            format,
+            batch_start,
+            detected_language.as_ref(),
+        );
+        println!("  -> saved to {}", output_path);
+    }
+
+    println!(
+        "Done! Transcribed {} file(s) in {:.2} sec",
+        audio_paths.len(),
+        batch_start.elapsed().as_secs_f32()
+    );
+}
+
+/// Live-captions 16 kHz mono f32 PCM read incrementally from stdin. Samples are
+/// buffered into overlapping windows (`STREAM_WINDOW_SECONDS` long, advancing by
+/// `STREAM_WINDOW_SECONDS - STREAM_OVERLAP_SECONDS` each time) and each window is
+/// transcribed as soon as enough audio has arrived. Token timestamps are used to
+/// skip the carried-over tail of the previous window so repeated words spanning a
+/// window boundary are only printed once.
+fn run_streaming(ctx: &Arc<WhisperContext>, language: &str, translate: bool) {
+    let window_samples = (STREAM_WINDOW_SECONDS * WHISPER_SAMPLE_RATE as f64) as usize;
+    let overlap_samples = (STREAM_OVERLAP_SECONDS * WHISPER_SAMPLE_RATE as f64) as usize;
+
+    let mut stdin = std::io::stdin().lock();
+    let mut raw = [0u8; 4096];
+    let mut pending_bytes: Vec<u8> = Vec::new();
+    let mut buffer: Vec<f32> = Vec::new();
+    let mut window_offset_cs: i64 = 0;
+    let mut printed_until_cs: i64 = 0;
+    let mut eof = false;
+
+    loop {
+        while !eof && buffer.len() < window_samples {
+            let n = stdin.read(&mut raw).expect("Error reading audio stream");
+            if n == 0 {
+                eof = true;
+                break;
+            }
+            pending_bytes.extend_from_slice(&raw[..n]);
+
+            let usable_len = pending_bytes.len() - pending_bytes.len() % 4;
+            for sample in pending_bytes[..usable_len].chunks_exact(4) {
+                buffer.push(f32::from_le_bytes([
+                    sample[0], sample[1], sample[2], sample[3],
+                ]));
+            }
+            pending_bytes.drain(0..usable_len);
+        }
+        if buffer.is_empty() {
+            break;
+        }
+
+        let params = build_params(language, translate);
+        let mut state = ctx.create_state().expect("Error creating state");
+        state
+            .full(params, &buffer)
+            .expect("Error during transcription");
+
+        printed_until_cs = print_new_tokens(&state, window_offset_cs, printed_until_cs);
+
+        if eof && buffer.len() <= overlap_samples {
+            break;
+        }
+
+        let keep_from = buffer.len().saturating_sub(overlap_samples);
+        window_offset_cs += (keep_from as f64 / WHISPER_SAMPLE_RATE as f64 * 100.0) as i64;
+        buffer.drain(0..keep_from);
+    }
+
+    println!();
+}
+
+/// Prints tokens from the just-transcribed window whose absolute start time (in
+/// centiseconds, `window_offset_cs` plus the token's own offset) is at or past
+/// `printed_until_cs`, i.e. tokens not already printed from the previous,
+/// overlapping window. Returns the updated `printed_until_cs`.
+fn print_new_tokens(
+    state: &whisper_rs::WhisperState,
+    window_offset_cs: i64,
+    printed_until_cs: i64,
+) -> i64 {
+    let mut printed_until_cs = printed_until_cs;
+    let num_segments = state.full_n_segments().expect("Error getting segments");
+    for i in 0..num_segments {
+        let num_tokens = state.full_n_tokens(i).expect("Error getting token count");
+        for j in 0..num_tokens {
+            let token_data = state
+                .full_get_token_data(i, j)
+                .expect("Error getting token data");
+            let abs_start = window_offset_cs + token_data.t0;
+            let abs_end = window_offset_cs + token_data.t1;
+            if !is_new_token(abs_start, printed_until_cs) {
+                continue;
+            }
+
+            let token_text = state
+                .full_get_token_text_lossy(i, j)
+                .expect("Error getting token text");
+            if token_text.starts_with('[') {
+                continue; // special token such as [_BEG_] or [_TT_xx]
+            }
+
+            print!("{}", token_text);
+            std::io::stdout().flush().unwrap();
+            printed_until_cs = printed_until_cs.max(abs_end);
+        }
     }
+    printed_until_cs
+}
+
+/// True if a token starting at `abs_start_cs` (absolute centiseconds) is new
+/// relative to `printed_until_cs`, i.e. not already printed from the previous,
+/// overlapping window.
+fn is_new_token(abs_start_cs: i64, printed_until_cs: i64) -> bool {
+    abs_start_cs >= printed_until_cs
+}
+
+/// Transcribes `audio_buffers` across a pool of `num_threads` workers that share
+/// a single read-only `WhisperContext`, each worker owning its own `WhisperState`
+/// so the large-v3 model weights are loaded once no matter how many buffers or
+/// threads are in play. Results are returned in the same order as `audio_buffers`.
+fn transcribe_parallel(
+    ctx: &Arc<WhisperContext>,
+    audio_buffers: &[Vec<f32>],
+    language: &str,
+    translate: bool,
+    num_threads: usize,
+) -> Vec<whisper_rs::WhisperState> {
+    let num_threads = num_threads.max(1).min(audio_buffers.len().max(1));
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<whisper_rs::WhisperState>>> =
+        Mutex::new((0..audio_buffers.len()).map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= audio_buffers.len() {
+                    break;
+                }
+
+                let params = build_params(language, translate);
+                let mut state = ctx.create_state().expect("Error creating state");
+                state
+                    .full(params, &audio_buffers[i])
+                    .expect("Error during transcription");
 
-    // 2. Load model
-    println!("[1/4] Loading model...");
-    let ctx = WhisperContext::new_with_params(MODEL_PATH, WhisperContextParameters::default())
-        .expect("Error loading model");
+                results.lock().unwrap()[i] = Some(state);
+            });
+        }
+    });
 
-    // 3. Load and check audio
-    println!("[2/4] Analyzing audio...");
-    let audio_data = load_audio(audio_path);
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|state| state.expect("Worker did not produce a result for this input"))
+        .collect()
+}
 
-    // 4. Setup parameters for English
+/// Shared transcription parameters used for every file in a batch. `language` is
+/// a Whisper language code, or `"auto"` to leave it unset so Whisper detects the
+/// spoken language itself. `translate` asks Whisper to emit English text
+/// regardless of the source language.
+fn build_params(language: &str, translate: bool) -> FullParams<'_, 'static> {
     let mut params = FullParams::new(SamplingStrategy::BeamSearch {
         beam_size: 5,
         patience: 1.5,
     });
-    params.set_language(Some("en"));
-    params.set_translate(false);
+    params.set_language(if language == "auto" {
+        None
+    } else {
+        Some(language)
+    });
+    params.set_translate(translate);
     params.set_suppress_blank(true);
     params.set_suppress_nst(true);
     params.set_token_timestamps(true);
+    params
+}
 
-    // 5. Transcription
-    println!("[3/4] Transcribing...");
-    let start_time = Instant::now();
-    let mut state = ctx.create_state().expect("Error creating state");
-    state
-        .full(params, &audio_data)
-        .expect("Error during transcription");
+/// Reports the language Whisper auto-detected for the current state, alongside
+/// its confidence, using the probability distribution over all known languages.
+fn detect_language(state: &mut whisper_rs::WhisperState) -> (String, f32) {
+    let (lang_id, probabilities) = state.lang_detect(0, 1).expect("Error detecting language");
+    let probability = probabilities[lang_id as usize];
+    let name = whisper_rs::whisper_lang_str(lang_id).to_string();
+    (name, probability)
+}
 
-    // 6. Save results
-    println!("[4/4] Saving...");
-    save_results(&state, output_path, start_time);
-    println!("Done! Results saved to {}", output_path);
+/// Output format selectable with `--format=txt|srt|vtt|json`, defaulting to plain text.
+#[derive(Clone, Copy)]
+enum OutputFormat {
+    Txt,
+    Srt,
+    Vtt,
+    Json,
 }
 
+impl OutputFormat {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "txt" => Some(OutputFormat::Txt),
+            "srt" => Some(OutputFormat::Srt),
+            "vtt" => Some(OutputFormat::Vtt),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Txt => "txt",
+            OutputFormat::Srt => "srt",
+            OutputFormat::Vtt => "vtt",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+/// Derives a sibling `<stem>_transcription.<ext>` output path from an input audio
+/// path, keeping it in the same directory as the input so batch runs over files
+/// that share a stem in different directories (e.g. `a/audio.wav`, `b/audio.wav`)
+/// don't clobber each other's output.
+fn output_path_for(audio_path: &str, format: OutputFormat) -> String {
+    let path = Path::new(audio_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let file_name = format!("{}_transcription.{}", stem, format.extension());
+    match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(parent) => parent.join(file_name).to_string_lossy().into_owned(),
+        None => file_name,
+    }
+}
+
+/// Decodes any Symphonia-supported container/codec (wav, mp3, aac, flac, m4a/mp4, ogg, ...),
+/// downmixes to mono and resamples to the 16 kHz mono f32 PCM Whisper expects.
 fn load_audio(path: &str) -> Vec<f32> {
-    let reader = WavReader::open(path).expect("Error reading WAV file");
-    let spec = reader.spec();
+    let file = File::open(path).unwrap_or_else(|e| panic!("Error opening {}: {}", path, e));
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
-    // Check format
-    if spec.channels != 1 || spec.sample_rate != 16000 {
-        panic!("Audio must be mono 16kHz. Convert with:\nffmpeg -i input.mp3 -ar 16000 -ac 1 -c:a pcm_s16le audio_en.wav");
+    let mut hint = Hint::new();
+    if let Some(ext) = Path::new(path).extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
     }
 
-    reader
-        .into_samples::<i16>()
-        .map(|s| s.unwrap() as f32 / 32768.0)
-        .collect()
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .expect("Error probing audio format");
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .expect("No supported audio track found")
+        .clone();
+    let track_id = track.id;
+    let source_sample_rate = track
+        .codec_params
+        .sample_rate
+        .expect("Audio track has no sample rate");
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .expect("Error creating audio decoder");
+
+    let mut mono_samples = Vec::new();
+    let mut sample_buf: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(_)) => break, // end of stream
+            Err(e) => panic!("Error reading packet: {}", e),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => panic!("Error decoding packet: {}", e),
+        };
+
+        if sample_buf.is_none() {
+            let spec = *decoded.spec();
+            let duration = decoded.capacity() as u64;
+            sample_buf = Some(SampleBuffer::<f32>::new(duration, spec));
+        }
+        downmix_to_mono(decoded, sample_buf.as_mut().unwrap(), &mut mono_samples);
+    }
+
+    resample_linear(&mono_samples, source_sample_rate, WHISPER_SAMPLE_RATE)
+}
+
+/// Interleaves `decoded` through `sample_buf` and averages channels into `out`.
+fn downmix_to_mono(
+    decoded: AudioBufferRef,
+    sample_buf: &mut SampleBuffer<f32>,
+    out: &mut Vec<f32>,
+) {
+    let channels = decoded.spec().channels.count();
+    sample_buf.copy_interleaved_ref(decoded);
+    for frame in sample_buf.samples().chunks(channels) {
+        out.push(frame.iter().sum::<f32>() / channels as f32);
+    }
+}
+
+/// Linear-interpolation resampler from `from_rate` to `to_rate`, mono in, mono out.
+fn resample_linear(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let ratio = from_rate as f64 / to_rate as f64;
+    let out_len = (samples.len() as f64 / ratio).round() as usize;
+    let mut out = Vec::with_capacity(out_len);
+    for i in 0..out_len {
+        let src_pos = i as f64 * ratio;
+        let idx = src_pos.floor() as usize;
+        let frac = src_pos - idx as f64;
+        let s0 = samples[idx];
+        let s1 = samples.get(idx + 1).copied().unwrap_or(s0);
+        out.push((s0 as f64 + (s1 as f64 - s0 as f64) * frac) as f32);
+    }
+    out
+}
+
+fn save_results(
+    state: &whisper_rs::WhisperState,
+    path: &str,
+    format: OutputFormat,
+    start_time: Instant,
+    detected_language: Option<&(String, f32)>,
+) {
+    match format {
+        OutputFormat::Txt => save_txt(state, path, start_time, detected_language),
+        OutputFormat::Srt => save_srt(state, path),
+        OutputFormat::Vtt => save_vtt(state, path),
+        OutputFormat::Json => save_json(state, path, detected_language),
+    }
+}
+
+/// Reads a segment's text, falling back to a lossy decode if Whisper emitted
+/// a segment that isn't valid UTF-8 rather than aborting the run.
+fn segment_text(state: &whisper_rs::WhisperState, i: i32) -> String {
+    match state.full_get_segment_text(i) {
+        Ok(text) => text,
+        Err(_) => {
+            eprintln!(
+                "Warning: segment {} is not valid UTF-8, decoding lossily",
+                i
+            );
+            state
+                .full_get_segment_text_lossy(i)
+                .expect("Error getting segment text")
+        }
+    }
 }
 
-fn save_results(state: &whisper_rs::WhisperState, path: &str, start_time: Instant) {
+fn save_txt(
+    state: &whisper_rs::WhisperState,
+    path: &str,
+    start_time: Instant,
+    detected_language: Option<&(String, f32)>,
+) {
     let mut file = File::create(path).expect("Error creating file");
     writeln!(file, "Transcription results:").unwrap();
+    if let Some((name, probability)) = detected_language {
+        writeln!(
+            file,
+            "Detected language: {} ({:.1}%)",
+            name,
+            probability * 100.0
+        )
+        .unwrap();
+    }
 
     let num_segments = state.full_n_segments().expect("Error getting segments");
     for i in 0..num_segments {
-        let text = state.full_get_segment_text(i).expect("Error getting text");
+        let text = segment_text(state, i);
         let start = state.full_get_segment_t0(i).unwrap() as f64 / 100.0;
         let end = state.full_get_segment_t1(i).unwrap() as f64 / 100.0;
 
@@ -86,3 +506,248 @@ fn save_results(state: &whisper_rs::WhisperState, path: &str, start_time: Instan
     )
     .unwrap();
 }
+
+fn save_srt(state: &whisper_rs::WhisperState, path: &str) {
+    let mut file = File::create(path).expect("Error creating file");
+
+    let mut cue_number = 1;
+    let num_segments = state.full_n_segments().expect("Error getting segments");
+    for i in 0..num_segments {
+        for cue in segment_cues(state, i) {
+            writeln!(file, "{}", cue_number).unwrap();
+            writeln!(
+                file,
+                "{} --> {}",
+                format_timestamp(cue.t0, ','),
+                format_timestamp(cue.t1, ',')
+            )
+            .unwrap();
+            writeln!(file, "{}\n", cue.text).unwrap();
+            cue_number += 1;
+        }
+    }
+}
+
+fn save_vtt(state: &whisper_rs::WhisperState, path: &str) {
+    let mut file = File::create(path).expect("Error creating file");
+    writeln!(file, "WEBVTT\n").unwrap();
+
+    let num_segments = state.full_n_segments().expect("Error getting segments");
+    for i in 0..num_segments {
+        for cue in segment_cues(state, i) {
+            writeln!(
+                file,
+                "{} --> {}",
+                format_timestamp(cue.t0, '.'),
+                format_timestamp(cue.t1, '.')
+            )
+            .unwrap();
+            writeln!(file, "{}\n", cue.text).unwrap();
+        }
+    }
+}
+
+/// Maximum length of a single subtitle cue, past which a segment is split at
+/// the nearest token boundary so long Whisper segments (which can run well
+/// past 10s) stay readable as captions.
+const MAX_CUE_DURATION_CENTISECONDS: i64 = 700;
+const MAX_CUE_CHARS: usize = 84;
+
+struct SubtitleCue {
+    t0: i64,
+    t1: i64,
+    text: String,
+}
+
+/// Splits a segment's tokens into one or more subtitle cues, starting a new
+/// cue at a token boundary whenever the current one would exceed
+/// `MAX_CUE_DURATION_CENTISECONDS` or `MAX_CUE_CHARS`.
+fn segment_cues(state: &whisper_rs::WhisperState, i: i32) -> Vec<SubtitleCue> {
+    let mut cues = Vec::new();
+    let mut cue_t0 = None;
+    let mut cue_t1 = 0;
+    let mut cue_text = String::new();
+
+    let num_tokens = state.full_n_tokens(i).expect("Error getting token count");
+    for j in 0..num_tokens {
+        let token_text = state
+            .full_get_token_text_lossy(i, j)
+            .expect("Error getting token text");
+        let trimmed = token_text.trim();
+        if trimmed.is_empty() || (trimmed.starts_with('[') && trimmed.ends_with(']')) {
+            continue; // skip special tokens such as [_BEG_] / [_TT_xx]
+        }
+        let token_data = state
+            .full_get_token_data(i, j)
+            .expect("Error getting token data");
+
+        let would_overflow = cue_t0.is_some_and(|t0| {
+            token_data.t1 - t0 > MAX_CUE_DURATION_CENTISECONDS
+                || cue_text.len() + token_text.len() > MAX_CUE_CHARS
+        });
+        if would_overflow {
+            cues.push(SubtitleCue {
+                t0: cue_t0.take().unwrap(),
+                t1: cue_t1,
+                text: std::mem::take(&mut cue_text).trim().to_string(),
+            });
+        }
+
+        cue_t0.get_or_insert(token_data.t0);
+        cue_t1 = token_data.t1;
+        cue_text.push_str(&token_text);
+    }
+    if let Some(t0) = cue_t0 {
+        cues.push(SubtitleCue {
+            t0,
+            t1: cue_t1,
+            text: cue_text.trim().to_string(),
+        });
+    }
+    cues
+}
+
+/// Formats a Whisper centisecond timestamp as `HH:MM:SS<sep>mmm`.
+fn format_timestamp(centiseconds: i64, ms_separator: char) -> String {
+    let total_ms = centiseconds * 10;
+    let ms = total_ms % 1000;
+    let total_secs = total_ms / 1000;
+    let secs = total_secs % 60;
+    let mins = (total_secs / 60) % 60;
+    let hours = total_secs / 3600;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        hours, mins, secs, ms_separator, ms
+    )
+}
+
+/// Serializes segments with per-token text, timing and decode confidence for
+/// downstream consumers that want a machine-readable transcript.
+fn save_json(
+    state: &whisper_rs::WhisperState,
+    path: &str,
+    detected_language: Option<&(String, f32)>,
+) {
+    let mut file = File::create(path).expect("Error creating file");
+
+    let num_segments = state.full_n_segments().expect("Error getting segments");
+    let mut segments_json = Vec::with_capacity(num_segments as usize);
+    for i in 0..num_segments {
+        let text = segment_text(state, i);
+        let start = state.full_get_segment_t0(i).unwrap() as f64 / 100.0;
+        let end = state.full_get_segment_t1(i).unwrap() as f64 / 100.0;
+
+        let num_tokens = state.full_n_tokens(i).expect("Error getting token count");
+        let mut tokens_json = Vec::with_capacity(num_tokens as usize);
+        for j in 0..num_tokens {
+            let token_text = state
+                .full_get_token_text_lossy(i, j)
+                .expect("Error getting token text");
+            let token_data = state
+                .full_get_token_data(i, j)
+                .expect("Error getting token data");
+            tokens_json.push(format!(
+                "{{\"word\": \"{}\", \"start\": {:.2}, \"end\": {:.2}, \"conf\": {:.4}}}",
+                json_escape(&token_text),
+                token_data.t0 as f64 / 100.0,
+                token_data.t1 as f64 / 100.0,
+                token_data.p
+            ));
+        }
+
+        segments_json.push(format!(
+            "{{\"text\": \"{}\", \"start\": {:.2}, \"end\": {:.2}, \"tokens\": [{}]}}",
+            json_escape(text.trim()),
+            start,
+            end,
+            tokens_json.join(", ")
+        ));
+    }
+
+    let detected_language_json = match detected_language {
+        Some((name, probability)) => format!(
+            "{{\"name\": \"{}\", \"probability\": {:.4}}}",
+            json_escape(name),
+            probability
+        ),
+        None => "null".to_string(),
+    };
+
+    writeln!(
+        file,
+        "{{\"detected_language\": {}, \"segments\": [{}]}}",
+        detected_language_json,
+        segments_json.join(", ")
+    )
+    .unwrap();
+}
+
+/// Escapes a string for embedding in a JSON string literal.
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample_linear_same_rate_is_passthrough() {
+        let samples = vec![0.1, 0.2, 0.3];
+        assert_eq!(resample_linear(&samples, 16000, 16000), samples);
+    }
+
+    #[test]
+    fn resample_linear_downsamples_by_half() {
+        let samples = vec![0.0, 1.0, 2.0, 3.0];
+        let out = resample_linear(&samples, 8000, 4000);
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0], 0.0);
+        assert_eq!(out[1], 2.0);
+    }
+
+    #[test]
+    fn resample_linear_empty_input() {
+        assert!(resample_linear(&[], 16000, 8000).is_empty());
+    }
+
+    #[test]
+    fn format_timestamp_srt_style() {
+        // 1h 1m 1.5s = 3661.5s = 366150 centiseconds
+        assert_eq!(format_timestamp(366150, ','), "01:01:01,500");
+    }
+
+    #[test]
+    fn format_timestamp_vtt_style_uses_dot_separator() {
+        assert_eq!(format_timestamp(0, '.'), "00:00:00.000");
+    }
+
+    #[test]
+    fn json_escape_escapes_control_and_special_chars() {
+        assert_eq!(json_escape("a\"b\\c\nd\te"), "a\\\"b\\\\c\\nd\\te");
+    }
+
+    #[test]
+    fn json_escape_passes_through_plain_text() {
+        assert_eq!(json_escape("hello world"), "hello world");
+    }
+
+    #[test]
+    fn is_new_token_rejects_tokens_already_printed() {
+        assert!(!is_new_token(100, 150));
+        assert!(is_new_token(150, 150));
+        assert!(is_new_token(200, 150));
+    }
+}